@@ -0,0 +1,7 @@
+-- Create changer settings table, one row per user
+CREATE TABLE changer_settings (
+    user_id INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    repeat_mode TEXT NOT NULL DEFAULT 'off' CHECK (repeat_mode IN ('off', 'one', 'all')),
+    shuffle BOOLEAN NOT NULL DEFAULT FALSE,
+    crossfade_secs INTEGER NOT NULL DEFAULT 0 CHECK (crossfade_secs BETWEEN 0 AND 12)
+);
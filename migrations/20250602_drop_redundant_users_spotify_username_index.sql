@@ -0,0 +1,5 @@
+-- spotify_username is already declared UNIQUE in the create migration, and
+-- SQLite backs that constraint with its own autoindex. That index serves
+-- lookups too, so the plain index only adds a second index to maintain on
+-- every write.
+DROP INDEX IF EXISTS idx_users_spotify_username;
@@ -0,0 +1,9 @@
+-- created_at and updated_at are NOT NULL DEFAULT CURRENT_TIMESTAMP from the
+-- create migration; keep updated_at current on every update as well
+CREATE TRIGGER users_set_updated_at
+AFTER UPDATE ON users
+FOR EACH ROW
+WHEN NEW.updated_at = OLD.updated_at
+BEGIN
+    UPDATE users SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
@@ -0,0 +1,226 @@
+use axum::{
+    Json, Router,
+    extract::{
+        Path, State,
+        rejection::{JsonRejection, PathRejection},
+    },
+    http::{HeaderValue, Method, StatusCode, header},
+    routing::get,
+};
+use serde::Deserialize;
+use std::env;
+use std::sync::Arc;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::error::AppError;
+use crate::{AppState, db, publish_user_list};
+
+// JSON body for creating a user
+#[derive(Deserialize)]
+struct CreateUserRequest {
+    spotify_username: String,
+}
+
+// JSON body for updating a user's changer settings
+#[derive(Deserialize)]
+struct UpdateSettingsRequest {
+    repeat_mode: String,
+    shuffle: bool,
+    crossfade_secs: i64,
+}
+
+// Routes for the JSON API, mounted under /api
+pub fn router() -> Router<Arc<AppState>> {
+    let router = Router::new()
+        .route("/users", get(list_users).post(create_user))
+        .route("/users/{id}", get(get_user).delete(delete_user))
+        .route(
+            "/users/{id}/settings",
+            get(get_settings).put(update_settings),
+        )
+        .fallback(not_found);
+
+    match cors_layer() {
+        Some(cors) => router.layer(cors),
+        None => router,
+    }
+}
+
+// CORS for the API from CORS_ALLOWED_ORIGINS, a comma-separated list of origins
+// or `*` to allow any. When unset no CORS headers are sent, so only same-origin
+// pages can call the API.
+fn cors_layer() -> Option<CorsLayer> {
+    let origins = env::var("CORS_ALLOWED_ORIGINS").ok()?;
+    let origins = origins.trim();
+    if origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if origins == "*" {
+        AllowOrigin::any()
+    } else {
+        let origins: Vec<HeaderValue> = origins
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| {
+                origin
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid origin in CORS_ALLOWED_ORIGINS: {origin}"))
+            })
+            .collect();
+        AllowOrigin::list(origins)
+    };
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([header::CONTENT_TYPE]),
+    )
+}
+
+// Unmatched API paths get a problem+json 404 rather than the HTML 404 page
+async fn not_found() -> AppError {
+    AppError::NotFound(String::from("No such API route"))
+}
+
+// List all users
+async fn list_users(State(state): State<Arc<AppState>>) -> Result<Json<Vec<db::User>>, AppError> {
+    let users = db::get_all_users(&state.db).await?;
+    Ok(Json(users))
+}
+
+// Create a user
+async fn create_user(
+    State(state): State<Arc<AppState>>,
+    payload: Result<Json<CreateUserRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<db::User>), AppError> {
+    let Json(request) = payload?;
+
+    let user = match db::create_user(&state.db, &request.spotify_username).await {
+        Ok(user) => user,
+        Err(db::UserError::Invalid(_)) => {
+            return Err(AppError::Invalid(String::from("Invalid Spotify username")));
+        }
+        Err(db::UserError::Duplicate(_)) => {
+            return Err(AppError::Conflict(String::from("User already exists")));
+        }
+        Err(db::UserError::Database(err)) => return Err(err.into()),
+    };
+
+    publish_user_list(&state).await;
+
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+// Get a single user
+async fn get_user(
+    State(state): State<Arc<AppState>>,
+    path: Result<Path<i64>, PathRejection>,
+) -> Result<Json<db::User>, AppError> {
+    let Path(id) = path?;
+
+    match db::get_user_by_id(&state.db, id).await? {
+        Some(user) => Ok(Json(user)),
+        None => Err(AppError::NotFound(String::from("User not found"))),
+    }
+}
+
+// Delete a user
+async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    path: Result<Path<i64>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = path?;
+
+    if !db::delete_user(&state.db, id).await? {
+        return Err(AppError::NotFound(String::from("User not found")));
+    }
+
+    publish_user_list(&state).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Get a user's changer settings, creating the defaults on first read
+async fn get_settings(
+    State(state): State<Arc<AppState>>,
+    path: Result<Path<i64>, PathRejection>,
+) -> Result<Json<db::ChangerSettings>, AppError> {
+    let Path(id) = path?;
+
+    let settings = db::get_settings(&state.db, id)
+        .await
+        .map_err(settings_error)?;
+    Ok(Json(settings))
+}
+
+// Replace a user's changer settings
+async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    path: Result<Path<i64>, PathRejection>,
+    payload: Result<Json<UpdateSettingsRequest>, JsonRejection>,
+) -> Result<Json<db::ChangerSettings>, AppError> {
+    let Path(id) = path?;
+    let Json(request) = payload?;
+
+    let settings = db::ChangerSettings {
+        user_id: id,
+        repeat_mode: request.repeat_mode.parse().map_err(settings_error)?,
+        shuffle: request.shuffle,
+        crossfade_secs: request.crossfade_secs,
+    };
+    let settings = db::update_settings(&state.db, &settings)
+        .await
+        .map_err(settings_error)?;
+
+    Ok(Json(settings))
+}
+
+// Map a settings error to its API response
+fn settings_error(err: db::SettingsError) -> AppError {
+    match err {
+        db::SettingsError::InvalidRepeatMode(_) | db::SettingsError::InvalidCrossfade(_) => {
+            AppError::Invalid(err.to_string())
+        }
+        db::SettingsError::UnknownUser(_) => AppError::NotFound(String::from("User not found")),
+        db::SettingsError::Database(err) => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    // Send a request with an empty body to the API router
+    async fn send(method: Method, uri: &str) -> axum::response::Response {
+        let app = router().with_state(AppState::for_tests().await);
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn malformed_user_id_is_a_problem() {
+        for (method, uri) in [
+            (Method::GET, "/users/abc"),
+            (Method::DELETE, "/users/1.5"),
+            (Method::GET, "/users/abc/settings"),
+        ] {
+            let response = send(method, uri).await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{uri}");
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                "application/problem+json",
+                "{uri}"
+            );
+        }
+    }
+}
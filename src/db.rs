@@ -1,10 +1,14 @@
 use sqlx::{
     migrate::MigrateDatabase,
     sqlite::{SqliteConnectOptions, SqlitePoolOptions},
-    Pool, Row, Sqlite,
+    Pool, QueryBuilder, Row, Sqlite,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
 
 // Database URL
 const DB_URL: &str = "sqlite:db.sqlite";
@@ -12,32 +16,249 @@ const DB_URL: &str = "sqlite:db.sqlite";
 // Database connection pool type
 pub type DbPool = Pool<Sqlite>;
 
+// Writer pool, plus a separate reader pool when `DATABASE_READ_URL` is set
+#[derive(Debug, Clone)]
+pub struct Db {
+    writer: DbPool,
+    reader: Option<DbPool>,
+}
+
+impl Db {
+    /// Pool for writes and for reads that must see them
+    pub fn writer(&self) -> &DbPool {
+        &self.writer
+    }
+
+    /// Pool for reads that can tolerate replica lag
+    pub fn reader(&self) -> &DbPool {
+        self.reader.as_ref().unwrap_or(&self.writer)
+    }
+
+    /// The reader pool, if it is distinct from the writer
+    pub fn separate_reader(&self) -> Option<&DbPool> {
+        self.reader.as_ref()
+    }
+
+    /// This database with reads served by the writer, for reads that must
+    /// include writes just made
+    pub fn primary(&self) -> Db {
+        Db {
+            writer: self.writer.clone(),
+            reader: None,
+        }
+    }
+}
+
+// Longest Spotify username we accept
+const MAX_SPOTIFY_USERNAME_LEN: usize = 64;
+
+// Longest crossfade between tracks, in seconds
+pub const MAX_CROSSFADE_SECS: i64 = 12;
+
+// Rows per multi-row INSERT, keeping well under SQLite's bind parameter limit
+const BULK_INSERT_CHUNK_SIZE: usize = 500;
+
+// Connection pool settings
+#[derive(Debug, Clone)]
+pub struct DbConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl DbConfig {
+    /// Load pool settings from the environment, using the defaults for unset values
+    ///
+    /// Reads `DATABASE_MAX_CONNECTIONS`, `DATABASE_MIN_CONNECTIONS` and
+    /// `DATABASE_ACQUIRE_TIMEOUT_SECS`.
+    pub fn from_env() -> Result<Self, sqlx::Error> {
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    // Load pool settings from whatever `lookup` returns for each variable name
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, sqlx::Error> {
+        let defaults = DbConfig::default();
+        let config = DbConfig {
+            max_connections: setting_or(
+                &lookup,
+                "DATABASE_MAX_CONNECTIONS",
+                defaults.max_connections,
+            )?,
+            min_connections: setting_or(
+                &lookup,
+                "DATABASE_MIN_CONNECTIONS",
+                defaults.min_connections,
+            )?,
+            acquire_timeout: Duration::from_secs(setting_or(
+                &lookup,
+                "DATABASE_ACQUIRE_TIMEOUT_SECS",
+                defaults.acquire_timeout.as_secs(),
+            )?),
+        };
+
+        if config.max_connections == 0 || config.min_connections > config.max_connections {
+            return Err(sqlx::Error::Configuration(
+                format!(
+                    "invalid pool size: min {} / max {}",
+                    config.min_connections, config.max_connections
+                )
+                .into(),
+            ));
+        }
+
+        Ok(config)
+    }
+
+    // Pool options for these settings
+    fn pool_options(&self) -> SqlitePoolOptions {
+        SqlitePoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+    }
+}
+
+// Parse a setting, falling back to a default when it's unset
+fn setting_or<T: FromStr>(
+    lookup: impl Fn(&str) -> Option<String>,
+    name: &str,
+    default: T,
+) -> Result<T, sqlx::Error> {
+    match lookup(name) {
+        Some(value) => value.trim().parse().map_err(|_| {
+            sqlx::Error::Configuration(format!("invalid {name}: '{value}'").into())
+        }),
+        None => Ok(default),
+    }
+}
+
 /// Initialize the database, running migrations if necessary
-pub async fn init_db() -> Result<DbPool, sqlx::Error> {
+///
+/// Also opens the read pool: a read-only pool on `DATABASE_READ_URL` when it
+/// is set, otherwise the primary pool is shared for reads.
+pub async fn init_db(config: &DbConfig) -> Result<Db, sqlx::Error> {
+    let read_url = env::var("DATABASE_READ_URL").ok();
+    open_db(DB_URL, read_url.as_deref(), config).await
+}
+
+// Open the writer pool on `url`, running migrations, and the reader pool on
+// `read_url`, or share the writer when there is no read URL
+async fn open_db(url: &str, read_url: Option<&str>, config: &DbConfig) -> Result<Db, sqlx::Error> {
     // Create database if it doesn't exist
-    if !Sqlite::database_exists(DB_URL).await.unwrap_or(false) {
-        Sqlite::create_database(DB_URL).await?;
+    if !Sqlite::database_exists(url).await.unwrap_or(false) {
+        Sqlite::create_database(url).await?;
     }
 
     // Set up connection options
-    let options = SqliteConnectOptions::from_str(DB_URL)?
+    let options = SqliteConnectOptions::from_str(url)?
         .create_if_missing(true)
         .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
 
     // Create connection pool
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect_with(options)
-        .await?;
+    let pool = config.pool_options().connect_with(options).await?;
 
     // Run migrations
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    Ok(pool)
+    let reader = match read_url {
+        Some(read_url) => Some(open_read_pool(read_url, config).await?),
+        None => None,
+    };
+
+    Ok(Db {
+        writer: pool,
+        reader,
+    })
+}
+
+// Open the read-only pool configured by `DATABASE_READ_URL`
+//
+// Read queries can be pointed at a separate database file, e.g. a replica
+// kept in sync by periodically restoring a backup. Such a replica is only as
+// fresh as its last sync, so reads served from it may not include recent
+// writes.
+async fn open_read_pool(read_url: &str, config: &DbConfig) -> Result<DbPool, sqlx::Error> {
+    // The replica is never written to, and migrations are the primary's job
+    let options = SqliteConnectOptions::from_str(read_url)?.read_only(true);
+
+    config.pool_options().connect_with(options).await
+}
+
+/// Open a fresh in-memory database on a single connection, for tests
+#[cfg(test)]
+pub async fn open_test_db() -> Db {
+    let config = DbConfig {
+        max_connections: 1,
+        ..DbConfig::default()
+    };
+    open_db("sqlite::memory:", None, &config)
+        .await
+        .expect("failed to open test database")
+}
+
+/// Checkpoint the WAL into the main database file and close the pools
+///
+/// Run at shutdown so an abrupt stop afterwards can't leave a large `-wal`
+/// file behind.
+pub async fn close_db(db: &Db) -> Result<(), sqlx::Error> {
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(db.writer())
+        .await?;
+    db.writer().close().await;
+    if let Some(reader) = db.separate_reader() {
+        reader.close().await;
+    }
+    Ok(())
+}
+
+/// Check the database connection is usable
+pub async fn ping(db: &Db) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(db.writer()).await?;
+    Ok(())
+}
+
+/// Get a user by ID
+pub async fn get_user_by_id(db: &Db, id: i64) -> Result<Option<User>, sqlx::Error> {
+    fetch_user_by_id(db.reader(), id).await
 }
 
-/// Get a user by Spotify username
-pub async fn get_user_by_spotify_username(
+// Get a user by ID from a specific pool
+async fn fetch_user_by_id(pool: &DbPool, id: i64) -> Result<Option<User>, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, spotify_username, created_at, updated_at
+        FROM users
+        WHERE id = ?
+        "#
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(row) = row {
+        Ok(Some(User {
+            id: row.try_get("id")?,
+            spotify_username: row.try_get("spotify_username")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+// Get a user by Spotify username from a specific pool
+async fn fetch_user_by_spotify_username(
     pool: &DbPool,
     spotify_username: &str,
 ) -> Result<Option<User>, sqlx::Error> {
@@ -65,10 +286,17 @@ pub async fn get_user_by_spotify_username(
 }
 
 /// Create a new user
+///
+/// Returns `UserError::Invalid` if the Spotify username isn't valid and
+/// `UserError::Duplicate` if it is already taken.
 pub async fn create_user(
-    pool: &DbPool,
+    db: &Db,
     spotify_username: &str,
-) -> Result<User, sqlx::Error> {
+) -> Result<User, UserError> {
+    let spotify_username = normalize_spotify_username(spotify_username)
+        .ok_or_else(|| UserError::Invalid(spotify_username.to_string()))?;
+    let spotify_username = spotify_username.as_str();
+
     // Insert user
     sqlx::query(
         r#"
@@ -77,18 +305,160 @@ pub async fn create_user(
         "#
     )
     .bind(spotify_username)
-    .execute(pool)
-    .await?;
+    .execute(db.writer())
+    .await
+    .map_err(|err| UserError::from_write(err, spotify_username))?;
 
-    // Get created user
-    match get_user_by_spotify_username(pool, spotify_username).await? {
+    // Get created user from the writer, since a replica may not have it yet.
+    // The row was just inserted, so not finding it is an internal error.
+    match fetch_user_by_spotify_username(db.writer(), spotify_username).await? {
         Some(user) => Ok(user),
-        None => Err(sqlx::Error::RowNotFound),
+        None => Err(sqlx::Error::RowNotFound.into()),
+    }
+}
+
+/// Change a user's Spotify username, returning the updated user
+///
+/// `updated_at` is bumped by the `users_set_updated_at` trigger.
+///
+/// Returns `Ok(None)` if the user doesn't exist, `UserError::Invalid` if the
+/// new username isn't valid and `UserError::Duplicate` if it is already taken.
+pub async fn update_user_username(
+    db: &Db,
+    id: i64,
+    new_username: &str,
+) -> Result<Option<User>, UserError> {
+    let new_username = normalize_spotify_username(new_username)
+        .ok_or_else(|| UserError::Invalid(new_username.to_string()))?;
+    let new_username = new_username.as_str();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE users
+        SET spotify_username = ?
+        WHERE id = ?
+        "#
+    )
+    .bind(new_username)
+    .bind(id)
+    .execute(db.writer())
+    .await
+    .map_err(|err| UserError::from_write(err, new_username))?;
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
     }
+
+    Ok(fetch_user_by_id(db.writer(), id).await?)
+}
+
+/// Delete a user by ID, returning whether a user was deleted
+pub async fn delete_user(db: &Db, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM users
+        WHERE id = ?
+        "#
+    )
+    .bind(id)
+    .execute(db.writer())
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Normalize a Spotify username, returning None if it isn't a valid username
+pub fn normalize_spotify_username(spotify_username: &str) -> Option<String> {
+    let trimmed = spotify_username.trim();
+    if trimmed.is_empty()
+        || trimmed.len() > MAX_SPOTIFY_USERNAME_LEN
+        || trimmed.chars().any(char::is_whitespace)
+    {
+        return None;
+    }
+
+    Some(trimmed.to_string())
+}
+
+/// Create many users in a single transaction
+///
+/// Names are validated and deduplicated before inserting. Repeats within the
+/// batch and names that already exist in the database are skipped and
+/// reported in `duplicates` rather than aborting the batch, so every name ends
+/// up in exactly one of `created`, `duplicates` or `invalid`.
+pub async fn create_users_bulk(
+    db: &Db,
+    names: &[String],
+) -> Result<ImportSummary, sqlx::Error> {
+    let mut result = ImportSummary::default();
+
+    // Validate and dedupe, keeping the input order
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::with_capacity(names.len());
+    for name in names {
+        match normalize_spotify_username(name) {
+            Some(name) => {
+                if seen.insert(name.clone()) {
+                    candidates.push(name);
+                } else {
+                    result.duplicates.push(name);
+                }
+            }
+            None => result.invalid.push(name.clone()),
+        }
+    }
+
+    // Insert everything in one transaction, one multi-row INSERT per chunk
+    let mut tx = db.writer().begin().await?;
+    for chunk in candidates.chunks(BULK_INSERT_CHUNK_SIZE) {
+        let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO users (spotify_username) ");
+        query.push_values(chunk, |mut row, name| {
+            row.push_bind(name.as_str());
+        });
+        query.push(
+            r#"
+            ON CONFLICT (spotify_username) DO NOTHING
+            RETURNING id, spotify_username, created_at, updated_at
+            "#,
+        );
+
+        let users: Vec<User> = query.build_query_as().fetch_all(&mut *tx).await?;
+        result.created.extend(users);
+    }
+    tx.commit().await?;
+
+    // Anything the insert didn't return already existed
+    let created: HashSet<&str> = result
+        .created
+        .iter()
+        .map(|user| user.spotify_username.as_str())
+        .collect();
+    result.duplicates.extend(
+        candidates
+            .iter()
+            .filter(|name| !created.contains(name.as_str()))
+            .cloned(),
+    );
+
+    Ok(result)
+}
+
+/// Count all users
+pub async fn count_users(db: &Db) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS count
+        FROM users
+        "#
+    )
+    .fetch_one(db.reader())
+    .await?;
+
+    row.try_get("count")
 }
 
 /// Get all users
-pub async fn get_all_users(pool: &DbPool) -> Result<Vec<User>, sqlx::Error> {
+pub async fn get_all_users(db: &Db) -> Result<Vec<User>, sqlx::Error> {
     let rows = sqlx::query(
         r#"
         SELECT id, spotify_username, created_at, updated_at
@@ -96,7 +466,7 @@ pub async fn get_all_users(pool: &DbPool) -> Result<Vec<User>, sqlx::Error> {
         ORDER BY id
         "#
     )
-    .fetch_all(pool)
+    .fetch_all(db.reader())
     .await?;
     
     let mut users = Vec::with_capacity(rows.len());
@@ -121,6 +491,126 @@ pub struct User {
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Get a user's changer settings, creating the defaults on first read
+///
+/// Always uses the writer, since the first read inserts the defaults.
+/// Returns `SettingsError::UnknownUser` if the user doesn't exist.
+pub async fn get_settings(db: &Db, user_id: i64) -> Result<ChangerSettings, SettingsError> {
+    let pool = db.writer();
+
+    // Lazily create the default settings row
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO changer_settings (user_id)
+        VALUES (?)
+        "#
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .map_err(|err| SettingsError::from_write(err, user_id))?;
+
+    let row = sqlx::query(
+        r#"
+        SELECT user_id, repeat_mode, shuffle, crossfade_secs
+        FROM changer_settings
+        WHERE user_id = ?
+        "#
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    let repeat_mode: String = row.try_get("repeat_mode")?;
+    Ok(ChangerSettings {
+        user_id: row.try_get("user_id")?,
+        repeat_mode: repeat_mode.parse()?,
+        shuffle: row.try_get("shuffle")?,
+        crossfade_secs: row.try_get("crossfade_secs")?,
+    })
+}
+
+/// Validate and save a user's changer settings
+///
+/// Returns `SettingsError::UnknownUser` if the user doesn't exist.
+pub async fn update_settings(
+    db: &Db,
+    settings: &ChangerSettings,
+) -> Result<ChangerSettings, SettingsError> {
+    settings.validate()?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO changer_settings (user_id, repeat_mode, shuffle, crossfade_secs)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT (user_id) DO UPDATE SET
+            repeat_mode = excluded.repeat_mode,
+            shuffle = excluded.shuffle,
+            crossfade_secs = excluded.crossfade_secs
+        "#
+    )
+    .bind(settings.user_id)
+    .bind(settings.repeat_mode.as_str())
+    .bind(settings.shuffle)
+    .bind(settings.crossfade_secs)
+    .execute(db.writer())
+    .await
+    .map_err(|err| SettingsError::from_write(err, settings.user_id))?;
+
+    Ok(settings.clone())
+}
+
+// Outcome of a bulk user creation
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    // Users that were inserted
+    pub created: Vec<User>,
+    // Usernames skipped because they already exist or repeat earlier in the batch
+    pub duplicates: Vec<String>,
+    // Entries rejected as invalid usernames
+    pub invalid: Vec<String>,
+}
+
+// Errors from creating or changing a user
+#[derive(Debug)]
+pub enum UserError {
+    // The Spotify username isn't valid
+    Invalid(String),
+    // Another user already has this Spotify username
+    Duplicate(String),
+    Database(sqlx::Error),
+}
+
+impl UserError {
+    // Classify an error from writing a username, detecting unique violations
+    fn from_write(err: sqlx::Error, spotify_username: &str) -> Self {
+        match err {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                UserError::Duplicate(spotify_username.to_string())
+            }
+            err => UserError::Database(err),
+        }
+    }
+}
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserError::Invalid(name) => write!(f, "'{name}' is not a valid Spotify username"),
+            UserError::Duplicate(name) => write!(f, "user '{name}' already exists"),
+            UserError::Database(err) => write!(f, "database error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for UserError {}
+
+impl From<sqlx::Error> for UserError {
+    fn from(err: sqlx::Error) -> Self {
+        UserError::Database(err)
+    }
+}
+
 // Implement FromRow for User to allow for conversion from database rows
 impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for User {
     fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
@@ -131,4 +621,386 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for User {
             updated_at: row.try_get("updated_at")?,
         })
     }
-}
\ No newline at end of file
+}
+
+// Changer repeat mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+impl RepeatMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepeatMode::Off => "off",
+            RepeatMode::One => "one",
+            RepeatMode::All => "all",
+        }
+    }
+}
+
+impl FromStr for RepeatMode {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(RepeatMode::Off),
+            "one" => Ok(RepeatMode::One),
+            "all" => Ok(RepeatMode::All),
+            other => Err(SettingsError::InvalidRepeatMode(other.to_string())),
+        }
+    }
+}
+
+// Per-user changer settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangerSettings {
+    pub user_id: i64,
+    pub repeat_mode: RepeatMode,
+    pub shuffle: bool,
+    pub crossfade_secs: i64,
+}
+
+impl ChangerSettings {
+    /// Check the settings are within their allowed bounds
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if !(0..=MAX_CROSSFADE_SECS).contains(&self.crossfade_secs) {
+            return Err(SettingsError::InvalidCrossfade(self.crossfade_secs));
+        }
+
+        Ok(())
+    }
+}
+
+// Errors from reading or updating changer settings
+#[derive(Debug)]
+pub enum SettingsError {
+    InvalidRepeatMode(String),
+    InvalidCrossfade(i64),
+    // No user with this ID, so there is nothing to attach settings to
+    UnknownUser(i64),
+    Database(sqlx::Error),
+}
+
+impl SettingsError {
+    // Classify an error from writing a settings row, detecting missing users
+    //
+    // `INSERT OR IGNORE` doesn't ignore foreign key failures, so an unknown
+    // user ID surfaces here rather than as an empty result.
+    fn from_write(err: sqlx::Error, user_id: i64) -> Self {
+        match err {
+            sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => {
+                SettingsError::UnknownUser(user_id)
+            }
+            err => SettingsError::Database(err),
+        }
+    }
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::InvalidRepeatMode(mode) => {
+                write!(f, "invalid repeat mode '{mode}', expected off, one or all")
+            }
+            SettingsError::InvalidCrossfade(secs) => {
+                write!(f, "crossfade must be between 0 and {MAX_CROSSFADE_SECS} seconds, got {secs}")
+            }
+            SettingsError::UnknownUser(user_id) => write!(f, "user {user_id} does not exist"),
+            SettingsError::Database(err) => write!(f, "database error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<sqlx::Error> for SettingsError {
+    fn from(err: sqlx::Error) -> Self {
+        SettingsError::Database(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn shared_pool_reads_see_writes() {
+        let db = open_test_db().await;
+
+        let user = create_user(&db, "alice").await.unwrap();
+
+        let users = get_all_users(&db).await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, user.id);
+        assert_eq!(users[0].spotify_username, "alice");
+        assert!(get_user_by_id(&db, user.id).await.unwrap().is_some());
+    }
+
+    // Settings for a user, with everything but the crossfade at its default
+    fn settings_with_crossfade(crossfade_secs: i64) -> ChangerSettings {
+        ChangerSettings {
+            user_id: 1,
+            repeat_mode: RepeatMode::Off,
+            shuffle: false,
+            crossfade_secs,
+        }
+    }
+
+    #[test]
+    fn crossfade_bounds_are_inclusive() {
+        assert!(settings_with_crossfade(0).validate().is_ok());
+        assert!(settings_with_crossfade(MAX_CROSSFADE_SECS).validate().is_ok());
+
+        for secs in [-1, MAX_CROSSFADE_SECS + 1] {
+            assert!(matches!(
+                settings_with_crossfade(secs).validate(),
+                Err(SettingsError::InvalidCrossfade(got)) if got == secs
+            ));
+        }
+    }
+
+    #[test]
+    fn repeat_mode_parses_known_modes_only() {
+        assert_eq!("off".parse::<RepeatMode>().unwrap(), RepeatMode::Off);
+        assert_eq!("one".parse::<RepeatMode>().unwrap(), RepeatMode::One);
+        assert_eq!("all".parse::<RepeatMode>().unwrap(), RepeatMode::All);
+
+        for mode in ["", "OFF", "shuffle"] {
+            assert!(matches!(
+                mode.parse::<RepeatMode>(),
+                Err(SettingsError::InvalidRepeatMode(got)) if got == mode
+            ));
+        }
+    }
+
+    // Number of stored settings rows for a user
+    async fn settings_rows(db: &Db, user_id: i64) -> i64 {
+        sqlx::query("SELECT COUNT(*) AS count FROM changer_settings WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(db.writer())
+            .await
+            .unwrap()
+            .get("count")
+    }
+
+    #[tokio::test]
+    async fn default_settings_are_created_on_first_read() {
+        let db = open_test_db().await;
+        let user = create_user(&db, "alice").await.unwrap();
+        assert_eq!(settings_rows(&db, user.id).await, 0);
+
+        let settings = get_settings(&db, user.id).await.unwrap();
+        assert_eq!(settings.user_id, user.id);
+        assert_eq!(settings.repeat_mode, RepeatMode::Off);
+        assert!(!settings.shuffle);
+        assert_eq!(settings.crossfade_secs, 0);
+        assert_eq!(settings_rows(&db, user.id).await, 1);
+
+        // A second read reuses the row
+        get_settings(&db, user.id).await.unwrap();
+        assert_eq!(settings_rows(&db, user.id).await, 1);
+    }
+
+    #[tokio::test]
+    async fn settings_for_unknown_user_are_rejected() {
+        let db = open_test_db().await;
+
+        assert!(matches!(
+            get_settings(&db, 999).await,
+            Err(SettingsError::UnknownUser(999))
+        ));
+
+        let settings = ChangerSettings {
+            user_id: 999,
+            ..settings_with_crossfade(0)
+        };
+        assert!(matches!(
+            update_settings(&db, &settings).await,
+            Err(SettingsError::UnknownUser(999))
+        ));
+    }
+
+    #[tokio::test]
+    async fn updated_settings_are_read_back() {
+        let db = open_test_db().await;
+        let user = create_user(&db, "alice").await.unwrap();
+
+        let settings = ChangerSettings {
+            user_id: user.id,
+            repeat_mode: RepeatMode::All,
+            shuffle: true,
+            crossfade_secs: MAX_CROSSFADE_SECS,
+        };
+        update_settings(&db, &settings).await.unwrap();
+
+        let stored = get_settings(&db, user.id).await.unwrap();
+        assert_eq!(stored.repeat_mode, RepeatMode::All);
+        assert!(stored.shuffle);
+        assert_eq!(stored.crossfade_secs, MAX_CROSSFADE_SECS);
+
+        // Out-of-range values are rejected without touching the stored row
+        let invalid = ChangerSettings {
+            user_id: user.id,
+            ..settings_with_crossfade(MAX_CROSSFADE_SECS + 1)
+        };
+        assert!(update_settings(&db, &invalid).await.is_err());
+        assert_eq!(get_settings(&db, user.id).await.unwrap().crossfade_secs, MAX_CROSSFADE_SECS);
+    }
+
+    #[tokio::test]
+    async fn duplicate_username_is_rejected() {
+        let db = open_test_db().await;
+        create_user(&db, "alice").await.unwrap();
+
+        assert!(matches!(
+            create_user(&db, "alice").await,
+            Err(UserError::Duplicate(name)) if name == "alice"
+        ));
+
+        // Normalization happens first, so padded names collide too
+        assert!(matches!(
+            create_user(&db, "  alice ").await,
+            Err(UserError::Duplicate(_))
+        ));
+        assert_eq!(count_users(&db).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn renaming_to_a_taken_username_is_rejected() {
+        let db = open_test_db().await;
+        create_user(&db, "alice").await.unwrap();
+        let bob = create_user(&db, "bob").await.unwrap();
+
+        assert!(matches!(
+            update_user_username(&db, bob.id, "alice").await,
+            Err(UserError::Duplicate(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn timestamps_are_set_on_create_and_bumped_on_edit() {
+        let db = open_test_db().await;
+        let before = chrono::Utc::now() - chrono::Duration::seconds(5);
+
+        let user = create_user(&db, "alice").await.unwrap();
+        assert!(user.created_at >= before);
+        assert_eq!(user.updated_at, user.created_at);
+
+        // Backdate the row. Setting updated_at explicitly must not fire the
+        // trigger, or it would overwrite the value we just set.
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET created_at = '2000-01-01 00:00:00', updated_at = '2000-01-01 00:00:00'
+            WHERE id = ?
+            "#
+        )
+        .bind(user.id)
+        .execute(db.writer())
+        .await
+        .unwrap();
+        let backdated = get_user_by_id(&db, user.id).await.unwrap().unwrap();
+        assert_eq!(backdated.updated_at.to_rfc3339(), "2000-01-01T00:00:00+00:00");
+
+        // A rename leaves updated_at alone, so the trigger has to bump it
+        let renamed = update_user_username(&db, user.id, "alicia").await.unwrap().unwrap();
+        assert_eq!(renamed.created_at, backdated.created_at);
+        assert!(renamed.updated_at >= before);
+    }
+
+    #[tokio::test]
+    async fn bulk_create_inserts_a_thousand_users_in_one_transaction() {
+        let db = open_test_db().await;
+        let names: Vec<String> = (0..1000).map(|i| format!("user{i}")).collect();
+
+        let started = std::time::Instant::now();
+        let summary = create_users_bulk(&db, &names).await.unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(summary.created.len(), 1000);
+        assert!(summary.duplicates.is_empty());
+        assert!(summary.invalid.is_empty());
+        assert_eq!(count_users(&db).await.unwrap(), 1000);
+        // Generous bound: row-by-row commits would be far slower than one transaction
+        assert!(elapsed < Duration::from_secs(5), "bulk insert took {elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn bulk_create_reports_existing_names_as_duplicates() {
+        let db = open_test_db().await;
+        create_user(&db, "alice").await.unwrap();
+
+        let names = ["alice", "bob", "bob", "not valid", "carol"].map(String::from);
+        let summary = create_users_bulk(&db, &names).await.unwrap();
+
+        // SQLite doesn't guarantee RETURNING order
+        let mut created: Vec<&str> = summary
+            .created
+            .iter()
+            .map(|user| user.spotify_username.as_str())
+            .collect();
+        created.sort_unstable();
+        assert_eq!(created, ["bob", "carol"]);
+        // The in-batch repeat first, then the name that already existed
+        assert_eq!(summary.duplicates, ["bob", "alice"]);
+        assert_eq!(summary.invalid, ["not valid"]);
+        assert_eq!(count_users(&db).await.unwrap(), 3);
+    }
+
+    // Load pool settings from a fixed set of variables
+    fn config_from(vars: &[(&str, &str)]) -> Result<DbConfig, sqlx::Error> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        DbConfig::from_lookup(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[tokio::test]
+    async fn pool_is_sized_from_settings() {
+        let config = config_from(&[
+            ("DATABASE_MAX_CONNECTIONS", "3"),
+            ("DATABASE_MIN_CONNECTIONS", "1"),
+            ("DATABASE_ACQUIRE_TIMEOUT_SECS", " 10 "),
+        ])
+        .unwrap();
+        assert_eq!(config.acquire_timeout, Duration::from_secs(10));
+
+        let db = open_db("sqlite::memory:", None, &config).await.unwrap();
+        assert_eq!(db.writer().options().get_max_connections(), 3);
+        assert_eq!(db.writer().options().get_min_connections(), 1);
+    }
+
+    #[test]
+    fn unset_settings_use_defaults() {
+        let config = config_from(&[]).unwrap();
+        let defaults = DbConfig::default();
+        assert_eq!(config.max_connections, defaults.max_connections);
+        assert_eq!(config.min_connections, defaults.min_connections);
+        assert_eq!(config.acquire_timeout, defaults.acquire_timeout);
+    }
+
+    #[test]
+    fn invalid_pool_settings_are_rejected() {
+        let cases: [&[(&str, &str)]; 3] = [
+            // min above max
+            &[
+                ("DATABASE_MAX_CONNECTIONS", "2"),
+                ("DATABASE_MIN_CONNECTIONS", "3"),
+            ],
+            // an empty pool
+            &[("DATABASE_MAX_CONNECTIONS", "0")],
+            &[("DATABASE_MAX_CONNECTIONS", "lots")],
+        ];
+
+        for vars in cases {
+            assert!(
+                matches!(config_from(vars), Err(sqlx::Error::Configuration(_))),
+                "{vars:?} should be rejected"
+            );
+        }
+    }
+}
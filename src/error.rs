@@ -0,0 +1,219 @@
+use axum::{
+    Json,
+    extract::rejection::{FormRejection, JsonRejection, PathRejection},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use minijinja::ErrorKind;
+use serde_json::json;
+use std::fmt;
+
+// Errors returned by request handlers, rendered as RFC 7807 problem+json
+#[derive(Debug)]
+pub enum AppError {
+    // A template is missing or failed to load
+    Template(minijinja::Error),
+    // A template loaded but failed to render
+    Render(minijinja::Error),
+    // A database query failed. Always a 500, even for RowNotFound: handlers
+    // report missing resources with NotFound, so a missing row here means a
+    // query that should have found one didn't.
+    Database(sqlx::Error),
+    // The request was malformed
+    BadRequest(String),
+    // The request was well-formed but its content isn't valid
+    Invalid(String),
+    // The requested resource doesn't exist
+    NotFound(String),
+    // The request conflicts with existing data
+    Conflict(String),
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::Template(_) | AppError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+        }
+    }
+
+    // Problem type slug and title for each kind of error
+    fn problem(&self) -> (&'static str, &'static str) {
+        match self {
+            AppError::Template(_) => ("template-unavailable", "Template unavailable"),
+            AppError::Render(_) => ("render-failed", "Render failed"),
+            AppError::Database(_) => ("database-error", "Database error"),
+            AppError::BadRequest(_) => ("bad-request", "Bad request"),
+            AppError::Invalid(_) => ("invalid-input", "Invalid input"),
+            AppError::NotFound(_) => ("not-found", "Not found"),
+            AppError::Conflict(_) => ("conflict", "Conflict"),
+        }
+    }
+
+    // Message shown to the client; internal details are only logged
+    fn public_message(&self) -> String {
+        match self {
+            AppError::Template(_) => String::from("Page template is unavailable"),
+            AppError::Render(_) => String::from("Failed to render page"),
+            AppError::Database(_) => String::from("Database error"),
+            AppError::BadRequest(message)
+            | AppError::Invalid(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message) => message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Template(err) => write!(f, "template error: {err}"),
+            AppError::Render(err) => write!(f, "render error: {err}"),
+            AppError::Database(err) => write!(f, "database error: {err}"),
+            AppError::BadRequest(message) => write!(f, "bad request: {message}"),
+            AppError::Invalid(message) => write!(f, "invalid input: {message}"),
+            AppError::NotFound(message) => write!(f, "not found: {message}"),
+            AppError::Conflict(message) => write!(f, "conflict: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<minijinja::Error> for AppError {
+    fn from(err: minijinja::Error) -> Self {
+        match err.kind() {
+            ErrorKind::TemplateNotFound | ErrorKind::SyntaxError => AppError::Template(err),
+            _ => AppError::Render(err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        AppError::Database(err)
+    }
+}
+
+// Extractor rejections become problem+json 400s rather than axum's plain text
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<FormRejection> for AppError {
+    fn from(rejection: FormRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!("Request failed: {self}");
+        }
+
+        let (slug, title) = self.problem();
+        let body = json!({
+            "type": format!("/problems/{slug}"),
+            "title": title,
+            "status": status.as_u16(),
+            "detail": self.public_message(),
+        });
+
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    // Render an error, returning its status, content type and JSON body
+    async fn render(err: AppError) -> (StatusCode, String, Value) {
+        let response = err.into_response();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, content_type, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn not_found_is_a_404_problem() {
+        let (status, content_type, body) =
+            render(AppError::NotFound(String::from("User not found"))).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(content_type, "application/problem+json");
+        assert_eq!(
+            body,
+            json!({
+                "type": "/problems/not-found",
+                "title": "Not found",
+                "status": 404,
+                "detail": "User not found",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn database_error_is_a_500_problem_without_internals() {
+        let (status, content_type, body) =
+            render(AppError::Database(sqlx::Error::PoolTimedOut)).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(content_type, "application/problem+json");
+        assert_eq!(
+            body,
+            json!({
+                "type": "/problems/database-error",
+                "title": "Database error",
+                "status": 500,
+                "detail": "Database error",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn conflict_is_a_409_problem() {
+        let (status, _, body) =
+            render(AppError::Conflict(String::from("User already exists"))).await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["type"], "/problems/conflict");
+        assert_eq!(body["status"], 409);
+        assert_eq!(body["detail"], "User already exists");
+    }
+
+    #[tokio::test]
+    async fn row_not_found_is_an_internal_error() {
+        let (status, _, body) = render(AppError::Database(sqlx::Error::RowNotFound)).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["type"], "/problems/database-error");
+    }
+}
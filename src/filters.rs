@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use minijinja::{Error, ErrorKind};
+use std::fmt::Write;
+
+// Default format for datetimeformat when none is given
+const DEFAULT_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+// Parse a timestamp as rendered from a serialized chrono DateTime (RFC 3339)
+fn parse_datetime(value: &str) -> Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(|err| {
+            Error::new(
+                ErrorKind::InvalidOperation,
+                format!("invalid datetime '{value}': {err}"),
+            )
+        })
+}
+
+/// Format a timestamp with a strftime-style format string
+///
+/// `{{ user.created_at | datetimeformat("%Y-%m-%d") }}`
+pub fn datetimeformat(value: String, format: Option<String>) -> Result<String, Error> {
+    let datetime = parse_datetime(&value)?;
+    let format = format.as_deref().unwrap_or(DEFAULT_DATETIME_FORMAT);
+
+    // Write rather than to_string(), which panics on an invalid format string
+    let mut formatted = String::new();
+    write!(formatted, "{}", datetime.format(format)).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("invalid datetime format '{format}'"),
+        )
+    })?;
+
+    Ok(formatted)
+}
+
+/// Describe a timestamp relative to now, e.g. "5 minutes ago"
+///
+/// `{{ user.created_at | humanize }}`
+pub fn humanize(value: String) -> Result<String, Error> {
+    let datetime = parse_datetime(&value)?;
+    Ok(humanize_at(datetime, Utc::now()))
+}
+
+// Describe `datetime` relative to `now`
+fn humanize_at(datetime: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - datetime).num_seconds();
+
+    let (count, unit) = match seconds {
+        ..60 => return String::from("just now"),
+        60..3_600 => (seconds / 60, "minute"),
+        3_600..86_400 => (seconds / 3_600, "hour"),
+        86_400..2_592_000 => (seconds / 86_400, "day"),
+        2_592_000..31_536_000 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural} ago")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::humanize_at;
+    use chrono::{DateTime, Duration, Utc};
+    use minijinja::context;
+
+    // Render a template string through the app's environment with `ts` set
+    fn render(template: &str, ts: &str) -> Result<String, minijinja::Error> {
+        crate::build_template_env().render_str(template, context! { ts => ts })
+    }
+
+    // Humanize a timestamp the given number of seconds before a fixed now
+    fn humanize_ago(seconds: i64) -> String {
+        let now: DateTime<Utc> = "2025-06-01T12:00:00Z".parse().unwrap();
+        humanize_at(now - Duration::seconds(seconds), now)
+    }
+
+    #[test]
+    fn datetimeformat_uses_default_format() {
+        let rendered = render("{{ ts | datetimeformat }}", "2025-06-01T12:34:56Z").unwrap();
+        assert_eq!(rendered, "2025-06-01 12:34");
+    }
+
+    #[test]
+    fn datetimeformat_uses_given_format() {
+        let rendered = render(
+            "{{ ts | datetimeformat('%Y-%m-%d') }}",
+            "2025-06-01T12:34:56+02:00",
+        )
+        .unwrap();
+        assert_eq!(rendered, "2025-06-01");
+    }
+
+    #[test]
+    fn datetimeformat_rejects_bad_input_without_panicking() {
+        assert!(render("{{ ts | datetimeformat('%Q') }}", "2025-06-01T12:34:56Z").is_err());
+        assert!(render("{{ ts | datetimeformat }}", "yesterday").is_err());
+    }
+
+    #[test]
+    fn humanize_boundaries() {
+        assert_eq!(humanize_ago(0), "just now");
+        assert_eq!(humanize_ago(59), "just now");
+        assert_eq!(humanize_ago(60), "1 minute ago");
+        assert_eq!(humanize_ago(3_599), "59 minutes ago");
+        assert_eq!(humanize_ago(3_600), "1 hour ago");
+        assert_eq!(humanize_ago(86_399), "23 hours ago");
+        assert_eq!(humanize_ago(86_400), "1 day ago");
+        assert_eq!(humanize_ago(2 * 86_400), "2 days ago");
+        assert_eq!(humanize_ago(2_592_000), "1 month ago");
+        assert_eq!(humanize_ago(31_536_000), "1 year ago");
+    }
+
+    #[test]
+    fn humanize_filter_renders_relative_time() {
+        let ts = (Utc::now() - Duration::days(3)).to_rfc3339();
+        assert_eq!(render("{{ ts | humanize }}", &ts).unwrap(), "3 days ago");
+        assert!(render("{{ ts | humanize }}", "yesterday").is_err());
+    }
+}
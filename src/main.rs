@@ -1,88 +1,490 @@
 use axum::{
-    Router,
-    extract::{Form, State},
-    response::{Html, IntoResponse},
-    routing::{get, post},
+    Json, Router,
+    extract::{
+        Form, Path, State,
+        rejection::{FormRejection, PathRejection},
+    },
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{delete, get, post, put},
 };
+use axum_server::tls_rustls::RustlsConfig;
 use dotenv::dotenv;
+use error::AppError;
+use futures::stream::{self, Stream, StreamExt};
 use minijinja::{Environment, path_loader};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::convert::Infallible;
+use std::env;
+use std::fmt::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::sync::{broadcast, watch};
+use tower_http::{
+    LatencyUnit,
+    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+};
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
 
+mod api;
 mod db;
+mod error;
+mod filters;
+
+// How many user list updates a slow SSE client may fall behind before it is dropped
+const USER_EVENTS_CAPACITY: usize = 16;
 
 // Define a struct to hold our application state
 struct AppState {
     templates: Environment<'static>,
-    db_pool: db::DbPool,
+    // Re-read templates from disk on every render (development only)
+    template_autoreload: bool,
+    // Writer pool, plus the read replica when one is configured
+    db: db::Db,
+    // Rendered user list fragments pushed to SSE clients
+    user_events: broadcast::Sender<String>,
+    // Flipped to true when the server starts shutting down
+    shutdown: watch::Sender<bool>,
+}
+
+impl AppState {
+    // State over a fresh in-memory database, for tests
+    #[cfg(test)]
+    async fn for_tests() -> Arc<Self> {
+        let (user_events, _) = broadcast::channel(USER_EVENTS_CAPACITY);
+        let (shutdown, _) = watch::channel(false);
+        Arc::new(AppState {
+            templates: build_template_env(),
+            template_autoreload: false,
+            db: db::open_test_db().await,
+            user_events,
+            shutdown,
+        })
+    }
+
+    // Render a template with the given context
+    fn render(&self, name: &str, ctx: minijinja::Value) -> Result<Html<String>, AppError> {
+        // With autoreload on, build a fresh environment so every render reads
+        // and parses the template from disk. That's a disk read and compile per
+        // request, which is fine while editing templates but wasteful in
+        // production, where the cached environment is used instead.
+        let fresh;
+        let templates = if self.template_autoreload {
+            fresh = build_template_env();
+            &fresh
+        } else {
+            &self.templates
+        };
+
+        let template = templates.get_template(name)?;
+        Ok(Html(template.render(ctx)?))
+    }
+}
+
+// Build the template environment, with every custom filter registered here
+fn build_template_env() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_loader(path_loader("templates"));
+    env.add_filter("datetimeformat", filters::datetimeformat);
+    env.add_filter("humanize", filters::humanize);
+    env
+}
+
+// Handler for the health check, verifying the database connection.
+// Deliberately avoids templates so it works even if they're missing.
+async fn health_handler(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match db::ping(&state.db).await {
+        Ok(()) => (StatusCode::OK, Json(json!({ "status": "ok" }))),
+        Err(err) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "error", "error": err.to_string() })),
+        ),
+    }
+}
+
+// Handler for Prometheus metrics, hand-rendered in the text exposition format.
+// A metric whose source fails is left out rather than failing the scrape.
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut body = String::new();
+
+    match db::count_users(&state.db).await {
+        Ok(count) => {
+            let _ = writeln!(body, "# HELP six_disc_changer_users Number of users.");
+            let _ = writeln!(body, "# TYPE six_disc_changer_users gauge");
+            let _ = writeln!(body, "six_disc_changer_users {count}");
+        }
+        Err(err) => tracing::error!("Failed to count users for metrics: {err}"),
+    }
+
+    let _ = writeln!(
+        body,
+        "# HELP six_disc_changer_db_pool_connections Database pool connections."
+    );
+    let _ = writeln!(body, "# TYPE six_disc_changer_db_pool_connections gauge");
+    write_pool_connections(&mut body, "writer", state.db.writer());
+    if let Some(reader) = state.db.separate_reader() {
+        write_pool_connections(&mut body, "reader", reader);
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+// Append the idle and active connection gauges for one pool
+fn write_pool_connections(body: &mut String, pool_name: &str, pool: &db::DbPool) {
+    let size = pool.size() as usize;
+    let idle = pool.num_idle();
+    let _ = writeln!(
+        body,
+        "six_disc_changer_db_pool_connections{{pool=\"{pool_name}\",state=\"idle\"}} {idle}"
+    );
+    let _ = writeln!(
+        body,
+        "six_disc_changer_db_pool_connections{{pool=\"{pool_name}\",state=\"active\"}} {}",
+        size.saturating_sub(idle)
+    );
+}
+
+// Let browsers keep pages but revalidate them against the ETag on every use
+const PAGE_CACHE_CONTROL: &str = "no-cache";
+
+// Tag a rendered page with an ETag, answering 304 if the client already has it
+//
+// The tag is a hash of the body, so it only needs to be stable for the
+// lifetime of a deployment.
+fn cached_page(headers: &HeaderMap, page: Html<String>) -> Response {
+    let mut hasher = DefaultHasher::new();
+    page.0.hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+        });
+
+    let cache_headers = [
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, PAGE_CACHE_CONTROL.to_string()),
+    ];
+    if not_modified {
+        (StatusCode::NOT_MODIFIED, cache_headers).into_response()
+    } else {
+        (cache_headers, page).into_response()
+    }
 }
 
 // Handler for the index route
-async fn index_handler(State(state): State<Arc<AppState>>) -> Html<String> {
-    let template = state.templates.get_template("index.html").unwrap();
-    let rendered = template.render(minijinja::context! {}).unwrap();
-    Html(rendered)
+async fn index_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let page = state.render("index.html", minijinja::context! {})?;
+    Ok(cached_page(&headers, page))
 }
 
 // Handler for the about route
-async fn about_handler(State(state): State<Arc<AppState>>) -> Html<String> {
-    let template = state.templates.get_template("about.html").unwrap();
-    let rendered = template.render(minijinja::context! {}).unwrap();
-    Html(rendered)
+async fn about_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let page = state.render("about.html", minijinja::context! {})?;
+    Ok(cached_page(&headers, page))
 }
 
 // Handler for the users page
-async fn users_handler(State(state): State<Arc<AppState>>) -> Html<String> {
-    let template = state.templates.get_template("users.html").unwrap();
-    let rendered = template.render(minijinja::context! {}).unwrap();
-    Html(rendered)
+async fn users_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let page = state.render("users.html", minijinja::context! {})?;
+    Ok(cached_page(&headers, page))
 }
 
-// Handler to list all users (for HTMX)
-async fn list_users_handler(State(state): State<Arc<AppState>>) -> Html<String> {
+// Render the user list fragment from the given database
+async fn render_user_list(state: &AppState, db: &db::Db) -> Result<Html<String>, AppError> {
     // Get all users from the database
-    let users = db::get_all_users(&state.db_pool).await.unwrap_or_default();
+    let users = db::get_all_users(db).await?;
 
     // Render just the user list portion
-    let template = state.templates.get_template("user_list.html").unwrap();
-    let rendered = template
-        .render(minijinja::context! {
+    state.render(
+        "user_list.html",
+        minijinja::context! {
             users => users
-        })
-        .unwrap();
+        },
+    )
+}
+
+// Push a freshly rendered user list to any connected SSE clients
+async fn publish_user_list(state: &AppState) {
+    // Skip the render when nobody is listening
+    if state.user_events.receiver_count() == 0 {
+        return;
+    }
+
+    // Called right after a write, so read from the writer; a replica may not
+    // have the change yet
+    match render_user_list(state, &state.db.primary()).await {
+        // Sending only fails if every client disconnected in the meantime
+        Ok(Html(rendered)) => {
+            let _ = state.user_events.send(rendered);
+        }
+        Err(err) => tracing::error!("Failed to publish user list: {err}"),
+    }
+}
+
+// Handler to list all users (for HTMX)
+async fn list_users_handler(State(state): State<Arc<AppState>>) -> Result<Html<String>, AppError> {
+    render_user_list(&state, &state.db).await
+}
+
+// Handler streaming user list updates as server-sent events
+async fn stream_users_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    // Subscribe before rendering the initial list so no update is missed
+    let receiver = state.user_events.subscribe();
+    let Html(initial) = render_user_list(&state, &state.db).await?;
 
-    Html(rendered)
+    // A client that falls more than USER_EVENTS_CAPACITY updates behind gets a
+    // Lagged error and is dropped; the browser's EventSource will reconnect and
+    // start again from a fresh list.
+    let updates = stream::unfold(receiver, |mut receiver| async move {
+        let rendered = receiver.recv().await.ok()?;
+        Some((rendered, receiver))
+    });
+
+    // End the stream on shutdown, otherwise it would hold graceful shutdown open
+    let mut shutdown = state.shutdown.subscribe();
+    let shutdown = async move {
+        let _ = shutdown.wait_for(|&stopping| stopping).await;
+    };
+
+    let events = stream::once(async move { initial })
+        .chain(updates)
+        .take_until(shutdown)
+        .map(|rendered| Ok(Event::default().event("users").data(rendered)));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Plain 404 page used if the 404 template itself can't be rendered
+const NOT_FOUND_FALLBACK: &str = "<!DOCTYPE html><html><head><title>Not Found</title></head>\
+    <body><h1>404 Not Found</h1><p><a href=\"/\">Home</a></p></body></html>";
+
+// Fallback handler for unmatched routes
+async fn not_found_handler(State(state): State<Arc<AppState>>) -> (StatusCode, Html<String>) {
+    // Never return an AppError here, so a broken 404 template can't cascade
+    let page = state
+        .render("404.html", minijinja::context! {})
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to render 404 page: {err}");
+            Html(String::from(NOT_FOUND_FALLBACK))
+        });
+
+    (StatusCode::NOT_FOUND, page)
 }
 
-// Form data for adding a user
+// Form data for adding or renaming a user
 #[derive(Deserialize)]
-struct AddUserForm {
+struct UserForm {
     spotify_username: String,
 }
 
 // Handler to add a new user
 async fn add_user_handler(
     State(state): State<Arc<AppState>>,
-    Form(form): Form<AddUserForm>,
-) -> impl IntoResponse {
+    form: Result<Form<UserForm>, FormRejection>,
+) -> Result<Response, AppError> {
+    let Form(form) = form?;
+
     // Add user to the database
-    match db::create_user(&state.db_pool, &form.spotify_username).await {
-        Ok(user) => {
-            // Render the individual user item for HTMX to append
-            let template = state.templates.get_template("user_list_item.html").unwrap();
-            let rendered = template
-                .render(minijinja::context! {
-                    user => user
-                })
-                .unwrap();
+    let user = match db::create_user(&state.db, &form.spotify_username).await {
+        Ok(user) => user,
+        Err(err) => return user_form_error(&state, ADD_USER_ERROR_TARGET, err, "add"),
+    };
 
-            Html(rendered)
+    // Render the individual user item for HTMX to append
+    let Html(rendered) = state.render(
+        "user_list_item.html",
+        minijinja::context! {
+            user => user
+        },
+    )?;
+
+    publish_user_list(&state).await;
+
+    // Clear any error left over from a previous attempt
+    Ok(Html(rendered + ADD_USER_ERROR_CLEAR).into_response())
+}
+
+// Error area of the add user form
+const ADD_USER_ERROR_TARGET: &str = "#user-form-error";
+
+// Out-of-band swap that empties the add user form's error area
+const ADD_USER_ERROR_CLEAR: &str = r#"<div id="user-form-error" hx-swap-oob="true"></div>"#;
+
+// Render a failed add or rename into the form's error area instead of the list
+fn user_form_error(
+    state: &AppState,
+    target: &str,
+    err: db::UserError,
+    action: &str,
+) -> Result<Response, AppError> {
+    let (status, message) = match err {
+        db::UserError::Invalid(_) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            String::from("Please enter a valid Spotify username"),
+        ),
+        db::UserError::Duplicate(_) => (StatusCode::CONFLICT, String::from("User already exists")),
+        db::UserError::Database(err) => {
+            tracing::error!("Failed to {action} user: {err}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to {action} user"),
+            )
         }
-        Err(_) => {
-            // Return an error message
-            Html(String::from("Failed to add user"))
+    };
+
+    let rendered = state.render(
+        "user_form_error.html",
+        minijinja::context! {
+            message => message
+        },
+    )?;
+
+    Ok((
+        status,
+        [("HX-Retarget", target), ("HX-Reswap", "innerHTML")],
+        rendered,
+    )
+        .into_response())
+}
+
+// Summary returned from a CSV import
+#[derive(Serialize)]
+struct ImportResponse {
+    inserted: usize,
+    skipped: usize,
+    failed: usize,
+    skipped_usernames: Vec<String>,
+    failed_rows: Vec<String>,
+}
+
+// Parse Spotify usernames from the first column of a CSV body, returning the
+// usernames and a description of each row that couldn't be read
+fn parse_username_csv(body: &str) -> (Vec<String>, Vec<String>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+
+    let mut usernames = Vec::new();
+    let mut malformed = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 1;
+        match record {
+            Ok(record) => match record.get(0) {
+                // Allow an optional header row
+                Some("spotify_username") if row == 1 => {}
+                Some(username) if !username.is_empty() => usernames.push(username.to_string()),
+                _ => malformed.push(format!("row {row}: missing username")),
+            },
+            Err(err) => malformed.push(format!("row {row}: {err}")),
         }
     }
+
+    (usernames, malformed)
+}
+
+// Handler to import users from a CSV body of Spotify usernames
+async fn import_users_handler(
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> Result<Json<ImportResponse>, AppError> {
+    let (usernames, mut failed_rows) = parse_username_csv(&body);
+
+    // Insert everything in one transaction, skipping existing usernames
+    let summary = db::create_users_bulk(&state.db, &usernames).await?;
+    failed_rows.extend(
+        summary
+            .invalid
+            .into_iter()
+            .map(|username| format!("invalid username: {username}")),
+    );
+
+    if !summary.created.is_empty() {
+        publish_user_list(&state).await;
+    }
+
+    Ok(Json(ImportResponse {
+        inserted: summary.created.len(),
+        skipped: summary.duplicates.len(),
+        failed: failed_rows.len(),
+        skipped_usernames: summary.duplicates,
+        failed_rows,
+    }))
+}
+
+// Handler to rename a user
+async fn update_user_handler(
+    State(state): State<Arc<AppState>>,
+    path: Result<Path<i64>, PathRejection>,
+    form: Result<Form<UserForm>, FormRejection>,
+) -> Result<Response, AppError> {
+    let Path(id) = path?;
+    let Form(form) = form?;
+
+    let user = match db::update_user_username(&state.db, id, &form.spotify_username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(AppError::NotFound(String::from("User not found"))),
+        Err(err) => {
+            let target = format!("#user-{id}-error");
+            return user_form_error(&state, &target, err, "rename");
+        }
+    };
+
+    publish_user_list(&state).await;
+
+    // Render the updated item for HTMX to swap in place, which also clears
+    // any error from a previous attempt
+    let rendered = state.render(
+        "user_list_item.html",
+        minijinja::context! {
+            user => user
+        },
+    )?;
+
+    Ok(rendered.into_response())
+}
+
+// Handler to delete a user
+async fn delete_user_handler(
+    State(state): State<Arc<AppState>>,
+    path: Result<Path<i64>, PathRejection>,
+) -> Result<Html<String>, AppError> {
+    let Path(id) = path?;
+
+    if !db::delete_user(&state.db, id).await? {
+        return Err(AppError::NotFound(String::from("User not found")));
+    }
+
+    publish_user_list(&state).await;
+
+    // Empty body so HTMX swaps the removed item out of the list
+    Ok(Html(String::new()))
 }
 
 #[tokio::main]
@@ -90,30 +492,308 @@ async fn main() {
     // Load .env file
     dotenv().ok();
 
+    // Log to stdout, filtered by RUST_LOG (info by default)
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
     // Set up the template environment
-    let mut env = Environment::new();
-    env.set_loader(path_loader("templates"));
+    let templates = build_template_env();
+    let template_autoreload = env::var("TEMPLATE_AUTORELOAD")
+        .map(|value| value == "true" || value == "1")
+        .unwrap_or(false);
+    if template_autoreload {
+        tracing::info!("Template autoreload enabled");
+    }
 
     // Initialize the database
-    let db_pool = db::init_db().await.expect("Failed to initialize database");
-    println!("Database initialized successfully");
+    let db_config = db::DbConfig::from_env().expect("Invalid database configuration");
+    let db = db::init_db(&db_config)
+        .await
+        .expect("Failed to initialize database");
+    tracing::info!("Database initialized successfully");
+    if env::var("DATABASE_READ_URL").is_ok() {
+        tracing::info!("Read queries will use DATABASE_READ_URL");
+    }
 
     // Create the application state
+    let (user_events, _) = broadcast::channel(USER_EVENTS_CAPACITY);
+    let (shutdown, _) = watch::channel(false);
     let state = Arc::new(AppState {
-        templates: env,
-        db_pool,
+        templates,
+        template_autoreload,
+        db,
+        user_events,
+        shutdown,
     });
 
     // Set up the routes
     let app = Router::new()
         .route("/", get(index_handler))
         .route("/about", get(about_handler))
+        .route("/health", get(health_handler))
+        .route("/metrics", get(metrics_handler))
         .route("/users", get(users_handler))
         .route("/users", post(add_user_handler))
+        .route("/users/import", post(import_users_handler))
+        .route("/users/{id}", put(update_user_handler))
+        .route("/users/{id}", delete(delete_user_handler))
         .route("/users/list", get(list_users_handler))
-        .with_state(state);
+        .route("/users/stream", get(stream_users_handler))
+        .nest("/api", api::router())
+        .fallback(not_found_handler)
+        // Log method, path, status and latency for every request
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .on_response(
+                    DefaultOnResponse::new()
+                        .level(Level::INFO)
+                        .latency_unit(LatencyUnit::Millis),
+                ),
+        )
+        .with_state(state.clone());
+
+    // On Ctrl-C or SIGTERM, stop accepting connections and let in-flight
+    // requests finish. Long-lived SSE streams are told to end as well.
+    let shutdown_state = state.clone();
+    let shutdown = async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down");
+        shutdown_state.shutdown.send_replace(true);
+    };
+
+    // Serve over TLS when a certificate and key are configured, plain HTTP otherwise
+    match load_tls_config().await {
+        Some(tls_config) => {
+            tracing::info!("Server starting on https://0.0.0.0:8080");
+            let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+            let handle = axum_server::Handle::new();
+            let shutdown_handle = handle.clone();
+            tokio::spawn(async move {
+                shutdown.await;
+                shutdown_handle.graceful_shutdown(None);
+            });
+
+            axum_server::bind_rustls(addr, tls_config)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+        }
+        None => {
+            tracing::info!("Server starting on http://0.0.0.0:8080");
+            let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+                .unwrap();
+        }
+    }
+
+    // Flush the WAL into the main database file before exiting
+    if let Err(err) = db::close_db(&state.db).await {
+        tracing::error!("Failed to checkpoint database on shutdown: {err}");
+    }
+    tracing::info!("Shutdown complete");
+}
+
+// Resolves when the process receives Ctrl-C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    println!("Server starting on http://0.0.0.0:8080");
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+// Load the TLS certificate and key from TLS_CERT_PATH and TLS_KEY_PATH (PEM).
+// Returns None when neither is set. The rustls config advertises HTTP/2 via
+// ALPN, so serving over TLS also enables HTTP/2 for clients that support it.
+async fn load_tls_config() -> Option<RustlsConfig> {
+    match (
+        env::var("TLS_CERT_PATH").ok(),
+        env::var("TLS_KEY_PATH").ok(),
+    ) {
+        (Some(cert_path), Some(key_path)) => {
+            let config = RustlsConfig::from_pem_file(&cert_path, &key_path)
+                .await
+                .unwrap_or_else(|err| {
+                    panic!("Failed to load TLS certificate {cert_path} and key {key_path}: {err}")
+                });
+            Some(config)
+        }
+        (None, None) => None,
+        _ => panic!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    // Serve a fixed page through cached_page with an optional If-None-Match
+    fn request_page(if_none_match: Option<&str>) -> Response {
+        let mut headers = HeaderMap::new();
+        if let Some(tags) = if_none_match {
+            headers.insert(header::IF_NONE_MATCH, tags.parse().unwrap());
+        }
+        cached_page(&headers, Html(String::from("<h1>About</h1>")))
+    }
+
+    fn etag(response: &Response) -> String {
+        response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn page_is_served_with_cache_headers() {
+        let response = request_page(None);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(etag(&response).starts_with('"'));
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            PAGE_CACHE_CONTROL
+        );
+    }
+
+    #[test]
+    fn repeated_request_with_matching_etag_is_not_modified() {
+        let tag = etag(&request_page(None));
+
+        let response = request_page(Some(&tag));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(etag(&response), tag);
+
+        let response = request_page(Some(&format!("\"stale\", {tag}")));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn weak_etag_match_is_not_modified() {
+        let tag = etag(&request_page(None));
+
+        let response = request_page(Some(&format!("W/{tag}")));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn mismatched_etag_gets_the_page() {
+        let response = request_page(Some("\"0000000000000000\""));
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn malformed_user_forms_are_problems() {
+        let app = Router::new()
+            .route("/users", post(add_user_handler))
+            .route("/users/{id}", put(update_user_handler))
+            .with_state(AppState::for_tests().await);
+
+        for (method, uri, body) in [
+            // missing spotify_username field
+            ("POST", "/users", "username=alice"),
+            ("PUT", "/users/1", "username=alice"),
+            // non-numeric id
+            ("PUT", "/users/abc", "spotify_username=alice"),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{method} {uri}");
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                "application/problem+json",
+                "{method} {uri}"
+            );
+        }
+    }
+
+    #[test]
+    fn csv_header_row_is_skipped() {
+        let (usernames, malformed) = parse_username_csv("spotify_username\nalice\nbob\n");
+        assert_eq!(usernames, ["alice", "bob"]);
+        assert!(malformed.is_empty());
+
+        // Only the first row can be a header
+        let (usernames, _) = parse_username_csv("alice\nspotify_username\n");
+        assert_eq!(usernames, ["alice", "spotify_username"]);
+    }
+
+    #[test]
+    fn csv_rows_without_a_username_are_reported() {
+        let (usernames, malformed) = parse_username_csv("alice\n,extra\nbob\n");
+        assert_eq!(usernames, ["alice", "bob"]);
+        assert_eq!(malformed, ["row 2: missing username"]);
+    }
+
+    #[tokio::test]
+    async fn import_accounts_for_every_row() {
+        let state = AppState::for_tests().await;
+        db::create_user(&state.db, "alice").await.unwrap();
+
+        let body = "spotify_username\nalice\nbob\nbob\n,extra\nnot valid\ncarol\n";
+        let Json(response) = import_users_handler(State(state), body.to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(response.inserted, 2);
+        assert_eq!(response.skipped, 2);
+        assert_eq!(response.failed, 2);
+        assert_eq!(response.skipped_usernames, ["bob", "alice"]);
+        assert_eq!(
+            response.failed_rows,
+            ["row 5: missing username", "invalid username: not valid"]
+        );
+    }
+
+    #[tokio::test]
+    async fn user_stream_ends_on_shutdown() {
+        let state = AppState::for_tests().await;
+        let response = stream_users_handler(State(state.clone()))
+            .await
+            .unwrap()
+            .into_response();
+        let mut body = response.into_body().into_data_stream();
+
+        // The initial list arrives while the server is running
+        let first = body.next().await.unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&first).starts_with("event: users"));
+
+        // Graceful shutdown waits on open streams, so this one has to end
+        state.shutdown.send_replace(true);
+        let drained = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while body.next().await.is_some() {}
+        })
+        .await;
+        assert!(drained.is_ok(), "user stream still open after shutdown");
+    }
 }